@@ -0,0 +1,552 @@
+//! Utilities for writing code that writes code.
+//!
+//! This crate factors out the bits of `xtask codegen` that are generic
+//! enough to be reused directly from `#[test] fn sourcegen_*()` functions
+//! living in the crate that actually owns the generated file. That way a
+//! subsystem can regenerate (and verify) its own output without having to
+//! go through the centralized `xtask` binary.
+
+use std::{
+    fmt, mem,
+    path::{Path, PathBuf},
+};
+
+use once_cell::sync::OnceCell;
+use xshell::{cmd, pushenv, read_file, write_file};
+
+pub fn project_root() -> PathBuf {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    PathBuf::from(dir).parent().unwrap().parent().unwrap().to_owned()
+}
+
+/// Recursively lists all files in `dir`, skipping hidden entries (those
+/// whose file name starts with `.`, e.g. `.git`).
+pub fn list_files(dir: &Path) -> Vec<PathBuf> {
+    let mut res = Vec::new();
+    let mut work = vec![dir.to_path_buf()];
+    while let Some(dir) = work.pop() {
+        let mut entries = dir.read_dir().unwrap().map(|it| it.unwrap().path()).collect::<Vec<_>>();
+        entries.retain(|it| match it.file_name().and_then(|it| it.to_str()) {
+            Some(name) => !name.starts_with('.'),
+            None => true,
+        });
+        for entry in entries {
+            if entry.is_dir() {
+                work.push(entry);
+            } else {
+                res.push(entry);
+            }
+        }
+    }
+    res
+}
+
+/// Like [`list_files`], but retains only `.rs` files.
+pub fn list_rust_files(dir: &Path) -> Vec<PathBuf> {
+    let mut res = list_files(dir);
+    res.retain(|it| it.extension().map(|it| it == "rs").unwrap_or(false));
+    res
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Overwrite,
+    Ensure,
+}
+
+/// A helper to update file on disk if it has changed.
+/// With `mode == Mode::Ensure`, still writes the new contents but then errors
+/// out, so that this can be used both to overwrite stale output and, from a
+/// test, to assert that the checked-in output is still up-to-date.
+pub fn ensure_file_contents(path: &Path, contents: &str, mode: Mode) -> anyhow::Result<()> {
+    match read_file(path) {
+        Ok(old_contents) if normalize(&old_contents) == normalize(contents) => {
+            return Ok(());
+        }
+        _ => (),
+    }
+    let return_error = match mode {
+        Mode::Overwrite => false,
+        Mode::Ensure => true,
+    };
+    eprintln!("updating {}", path.display());
+    write_file(path, contents)?;
+
+    return if return_error {
+        let path = path.strip_prefix(&project_root()).unwrap_or(path);
+        anyhow::bail!("`{}` was not up-to-date, updating", path.display());
+    } else {
+        Ok(())
+    };
+
+    fn normalize(s: &str) -> String {
+        s.replace("\r\n", "\n")
+    }
+}
+
+const PREAMBLE: &str = "Generated file, do not edit by hand, see `xtask/src/codegen`";
+
+const TOOLCHAIN: &str = "stable";
+
+/// Formats `text` with `rustfmt`, short-circuiting the (slow) process spawn
+/// if we've already formatted this exact text with this exact `rustfmt.toml`
+/// and toolchain before. The result is cached on disk under `target/`, keyed
+/// by a hash of those three inputs, mirroring the way [`ensure_file_contents`]
+/// skips writing when nothing changed.
+pub fn reformat(text: &str) -> anyhow::Result<String> {
+    let rustfmt_toml = project_root().join("rustfmt.toml");
+    let cache_path = cache_path(text, &rustfmt_toml);
+    if let Ok(cached) = read_file(&cache_path) {
+        if is_valid_cache_entry(&cached) {
+            return Ok(cached);
+        }
+    }
+
+    let _e = pushenv("RUSTUP_TOOLCHAIN", TOOLCHAIN);
+    ensure_rustfmt()?;
+    let stdout = cmd!("rustfmt --config-path {rustfmt_toml} --config fn_single_line=true")
+        .stdin(text)
+        .read()?;
+    let res = format!("//! {}\n\n{}\n", PREAMBLE, stdout);
+
+    write_cache(&cache_path, &res);
+
+    Ok(res)
+}
+
+fn cache_path(text: &str, rustfmt_toml: &Path) -> PathBuf {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    read_file(rustfmt_toml).unwrap_or_default().hash(&mut hasher);
+    TOOLCHAIN.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    project_root().join("target").join("sourcegen-cache").join(format!("{:016x}.rs", hash))
+}
+
+/// A cache hit is only trusted if it looks like a complete, well-formed
+/// `reformat` output. This guards against a reader observing a cache entry
+/// that a concurrent or killed writer left half-written.
+fn is_valid_cache_entry(contents: &str) -> bool {
+    contents.starts_with(&format!("//! {}", PREAMBLE)) && contents.ends_with('\n')
+}
+
+/// Writes `contents` to `cache_path` via a temp file + rename, so a reader
+/// can never observe a partially-written cache entry: `rename` is atomic,
+/// so the path either doesn't exist yet or already holds the full contents.
+fn write_cache(cache_path: &Path, contents: &str) {
+    let Some(parent) = cache_path.parent() else { return };
+    if std::fs::create_dir_all(parent).is_err() {
+        return;
+    }
+    let tmp_path = parent.join(format!(".tmp-{}-{}", std::process::id(), unique_suffix()));
+    if write_file(&tmp_path, contents).is_ok() {
+        let _ = std::fs::rename(&tmp_path, cache_path);
+    }
+    let _ = std::fs::remove_file(&tmp_path);
+}
+
+/// A process-unique, monotonically increasing counter used to keep
+/// concurrent writers' temp file names from colliding with each other.
+fn unique_suffix() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    COUNTER.fetch_add(1, Ordering::Relaxed)
+}
+
+fn ensure_rustfmt() -> anyhow::Result<()> {
+    let version = cmd!("rustfmt --version").read().unwrap_or_default();
+    if !version.contains("stable") {
+        anyhow::bail!(
+            "Failed to run rustfmt from toolchain 'stable'. \
+             Please run `rustup component add rustfmt --toolchain stable` to install it.",
+        );
+    }
+    Ok(())
+}
+
+pub fn extract_comment_blocks(text: &str) -> Vec<Vec<String>> {
+    do_extract_comment_blocks(text, false).into_iter().map(|(_line, block)| block).collect()
+}
+
+pub fn extract_comment_blocks_with_empty_lines(tag: &str, text: &str) -> Vec<CommentBlock> {
+    assert!(tag.starts_with(char::is_uppercase));
+    let tag = format!("{}:", tag);
+    let mut res = Vec::new();
+    for (line, mut block) in do_extract_comment_blocks(text, true) {
+        let first = block.remove(0);
+        if first.starts_with(&tag) {
+            let id = first[tag.len()..].trim().to_string();
+            let block = CommentBlock { id, line, contents: block };
+            res.push(block);
+        }
+    }
+    res
+}
+
+pub struct CommentBlock {
+    pub id: String,
+    pub line: usize,
+    pub contents: Vec<String>,
+}
+
+fn do_extract_comment_blocks(
+    text: &str,
+    allow_blocks_with_empty_lines: bool,
+) -> Vec<(usize, Vec<String>)> {
+    let mut res = Vec::new();
+
+    let prefix = "// ";
+    let lines = text.lines().map(str::trim_start);
+
+    let mut block = (0, vec![]);
+    for (line_num, line) in lines.enumerate() {
+        if line == "//" && allow_blocks_with_empty_lines {
+            block.1.push(String::new());
+            continue;
+        }
+
+        let is_comment = line.starts_with(prefix);
+        if is_comment {
+            block.1.push(line[prefix.len()..].to_string());
+        } else {
+            if !block.1.is_empty() {
+                res.push(mem::take(&mut block));
+            }
+            block.0 = line_num + 2;
+        }
+    }
+    if !block.1.is_empty() {
+        res.push(block)
+    }
+    res
+}
+
+/// Identifies the GitHub repo (and ref) that generated doc links should
+/// point at. Defaults to whatever `origin` and `HEAD` say, but generators
+/// can build one explicitly to pin docs built from a tag to that tag rather
+/// than to the commit that happened to produce them.
+#[derive(Debug, Clone)]
+pub struct RepoConfig {
+    pub org: String,
+    pub repo: String,
+    pub git_ref: String,
+}
+
+impl RepoConfig {
+    /// Reads the `origin` remote and the current commit via `git`, caching
+    /// the result so we only shell out once per process.
+    ///
+    /// CI that builds docs from a tag can override any of the three fields
+    /// without touching the checkout, via `SOURCEGEN_REPO_ORG`,
+    /// `SOURCEGEN_REPO_NAME` and `SOURCEGEN_REPO_REF`.
+    pub fn discover() -> RepoConfig {
+        static CACHE: OnceCell<RepoConfig> = OnceCell::new();
+        CACHE.get_or_init(Self::discover_uncached).clone()
+    }
+
+    fn discover_uncached() -> RepoConfig {
+        let git = Self::from_git();
+        RepoConfig {
+            org: std::env::var("SOURCEGEN_REPO_ORG").unwrap_or(git.org),
+            repo: std::env::var("SOURCEGEN_REPO_NAME").unwrap_or(git.repo),
+            git_ref: std::env::var("SOURCEGEN_REPO_REF").unwrap_or(git.git_ref),
+        }
+    }
+
+    fn from_git() -> RepoConfig {
+        let origin = cmd!("git remote get-url origin").read().unwrap_or_default();
+        let (org, repo) = parse_github_remote(&origin)
+            .unwrap_or_else(|| ("rust-analyzer".to_string(), "rust-analyzer".to_string()));
+        let git_ref = cmd!("git rev-parse HEAD").read().unwrap_or_else(|_| "master".to_string());
+        RepoConfig { org, repo, git_ref }
+    }
+}
+
+fn parse_github_remote(url: &str) -> Option<(String, String)> {
+    let url = url.trim().trim_end_matches(".git");
+    let tail = url.rsplit_once("github.com")?.1;
+    let tail = tail.trim_start_matches(':').trim_start_matches('/').trim_end_matches('/');
+    let (org, repo) = tail.split_once('/')?;
+    Some((org.to_string(), repo.to_string()))
+}
+
+#[derive(Debug)]
+pub struct Location {
+    file: PathBuf,
+    line: usize,
+    repo_config: RepoConfig,
+}
+
+impl Location {
+    pub fn new(file: PathBuf, line: usize) -> Self {
+        Self::with_repo_config(file, line, RepoConfig::discover())
+    }
+
+    /// Like [`Location::new`], but pins the doc link to an explicit repo
+    /// and ref instead of the one discovered from the local checkout.
+    pub fn with_repo_config(file: PathBuf, line: usize, repo_config: RepoConfig) -> Self {
+        Self { file, line, repo_config }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.file.strip_prefix(&project_root()).unwrap().display().to_string();
+        let path = path.replace('\\', "/");
+        let name = self.file.file_name().unwrap();
+        let RepoConfig { org, repo, git_ref } = &self.repo_config;
+        write!(
+            f,
+            "https://github.com/{}/{}/blob/{}/{}#L{}[{}]",
+            org,
+            repo,
+            git_ref,
+            path,
+            self.line,
+            name.to_str().unwrap()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extract_comment_blocks_with_empty_lines_parses_tag_and_id() {
+        let text = "
+fn f() {}
+
+// Diagnostic: unresolved-import
+//
+// This diagnostic is triggered if an import path cannot be resolved.
+struct S;
+
+// not tagged, should be ignored
+struct T;
+";
+        let blocks = extract_comment_blocks_with_empty_lines("Diagnostic", text);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].id, "unresolved-import");
+        assert_eq!(
+            blocks[0].contents,
+            vec![
+                String::new(),
+                "This diagnostic is triggered if an import path cannot be resolved."
+                    .to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_comment_blocks_with_empty_lines_skips_other_tags() {
+        let text = "
+// Assist: add_braces
+//
+// Adds braces.
+struct S;
+";
+        assert!(extract_comment_blocks_with_empty_lines("Diagnostic", text).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "assertion failed")]
+    fn extract_comment_blocks_with_empty_lines_requires_capitalized_tag() {
+        extract_comment_blocks_with_empty_lines("diagnostic", "");
+    }
+
+    #[test]
+    fn ensure_file_contents_overwrite_writes_without_erroring() {
+        let dir = std::env::temp_dir()
+            .join(format!("sourcegen-ensure-file-contents-overwrite-{}", unique_suffix()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("generated.rs");
+
+        ensure_file_contents(&path, "stale", Mode::Overwrite).unwrap();
+        let result = ensure_file_contents(&path, "fresh", Mode::Overwrite);
+        let contents = read_file(&path).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(contents, "fresh");
+    }
+
+    #[test]
+    fn ensure_file_contents_ensure_errors_when_stale() {
+        let dir = std::env::temp_dir()
+            .join(format!("sourcegen-ensure-file-contents-ensure-{}", unique_suffix()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("generated.rs");
+        std::fs::write(&path, "stale").unwrap();
+
+        let result = ensure_file_contents(&path, "fresh", Mode::Ensure);
+        let contents = read_file(&path).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        // Ensure still writes the fresh contents; it only reports the
+        // staleness so CI can fail the build, not that the write was skipped.
+        assert_eq!(contents, "fresh");
+    }
+
+    #[test]
+    fn ensure_file_contents_is_a_noop_when_unchanged() {
+        let dir = std::env::temp_dir()
+            .join(format!("sourcegen-ensure-file-contents-noop-{}", unique_suffix()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("generated.rs");
+        std::fs::write(&path, "same").unwrap();
+
+        let result = ensure_file_contents(&path, "same", Mode::Ensure);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn list_files_skips_hidden_entries_and_descends_into_subdirs() {
+        let dir =
+            std::env::temp_dir().join(format!("sourcegen-list-files-test-{}", unique_suffix()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        std::fs::write(dir.join("visible.rs"), "").unwrap();
+        std::fs::write(dir.join("sub").join("nested.rs"), "").unwrap();
+        std::fs::write(dir.join("sub").join("nested.txt"), "").unwrap();
+        std::fs::write(dir.join(".hidden"), "").unwrap();
+        std::fs::write(dir.join(".git").join("HEAD"), "").unwrap();
+
+        let mut files = list_files(&dir);
+        files.sort();
+        let mut rust_files = list_rust_files(&dir);
+        rust_files.sort();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            files,
+            vec![
+                dir.join("sub").join("nested.rs"),
+                dir.join("sub").join("nested.txt"),
+                dir.join("visible.rs"),
+            ]
+        );
+        assert_eq!(rust_files, vec![dir.join("sub").join("nested.rs"), dir.join("visible.rs")]);
+    }
+
+    #[test]
+    fn parse_github_remote_https() {
+        assert_eq!(
+            parse_github_remote("https://github.com/kitamstudios/rust"),
+            Some(("kitamstudios".to_string(), "rust".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_github_remote_https_with_git_suffix() {
+        assert_eq!(
+            parse_github_remote("https://github.com/kitamstudios/rust.git"),
+            Some(("kitamstudios".to_string(), "rust".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_github_remote_https_trailing_slash() {
+        assert_eq!(
+            parse_github_remote("https://github.com/kitamstudios/rust/"),
+            Some(("kitamstudios".to_string(), "rust".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_github_remote_ssh() {
+        assert_eq!(
+            parse_github_remote("git@github.com:kitamstudios/rust.git"),
+            Some(("kitamstudios".to_string(), "rust".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_github_remote_non_github_host() {
+        assert_eq!(parse_github_remote("https://gitlab.com/kitamstudios/rust"), None);
+    }
+
+    #[test]
+    fn parse_github_remote_garbage() {
+        assert_eq!(parse_github_remote(""), None);
+    }
+
+    #[test]
+    fn repo_config_env_vars_override_git_discovery() {
+        // Exercise `discover_uncached` directly rather than `discover`,
+        // since the latter caches its result for the lifetime of the
+        // process and so can't be re-exercised by a second test.
+        std::env::set_var("SOURCEGEN_REPO_ORG", "kitamstudios");
+        std::env::set_var("SOURCEGEN_REPO_NAME", "rust");
+        std::env::set_var("SOURCEGEN_REPO_REF", "v1.2.3");
+
+        let repo_config = RepoConfig::discover_uncached();
+
+        std::env::remove_var("SOURCEGEN_REPO_ORG");
+        std::env::remove_var("SOURCEGEN_REPO_NAME");
+        std::env::remove_var("SOURCEGEN_REPO_REF");
+
+        assert_eq!(repo_config.org, "kitamstudios");
+        assert_eq!(repo_config.repo, "rust");
+        assert_eq!(repo_config.git_ref, "v1.2.3");
+    }
+
+    #[test]
+    fn is_valid_cache_entry_accepts_well_formed_output() {
+        assert!(is_valid_cache_entry(&format!("//! {}\n\nfn f() {{}}\n", PREAMBLE)));
+    }
+
+    #[test]
+    fn is_valid_cache_entry_rejects_torn_writes() {
+        // No preamble at all, so not something `reformat` wrote.
+        assert!(!is_valid_cache_entry("fn f("));
+        // Preamble present but truncated before the trailing newline.
+        assert!(!is_valid_cache_entry(&format!("//! {}\n\nfn f() {{}", PREAMBLE)));
+    }
+
+    #[test]
+    fn write_cache_then_read_round_trips() {
+        let dir = std::env::temp_dir()
+            .join(format!("sourcegen-write-cache-test-{}", unique_suffix()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("entry.rs");
+        let contents = format!("//! {}\n\nfn f() {{}}\n", PREAMBLE);
+
+        write_cache(&path, &contents);
+        let read_back = read_file(&path).unwrap();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(read_back, contents);
+        assert!(is_valid_cache_entry(&read_back));
+    }
+
+    #[test]
+    fn write_cache_leaves_no_temp_file_behind() {
+        let dir = std::env::temp_dir()
+            .join(format!("sourcegen-write-cache-tmp-test-{}", unique_suffix()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("entry.rs");
+
+        write_cache(&path, "//! irrelevant\n");
+        let leftovers = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|it| it.unwrap().file_name())
+            .filter(|name| name.to_string_lossy().starts_with(".tmp-"))
+            .count();
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(leftovers, 0);
+    }
+}
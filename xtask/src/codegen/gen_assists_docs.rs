@@ -0,0 +1,72 @@
+//! Generates `generated_assists.adoc` documentation from doc comments on
+//! assists defined in `ide-assists`, and a `tests/generated.rs` listing each
+//! assist's id and source location as comments; no test functions are
+//! generated.
+
+use std::{fmt, fs, path::PathBuf};
+
+use crate::{
+    codegen::{
+        self, extract_comment_blocks_with_empty_lines, list_rust_files, Location, Mode,
+        RepoConfig,
+    },
+    project_root, Result,
+};
+
+pub(crate) fn generate_assists_docs(mode: Mode, repo_config: &RepoConfig) -> Result<()> {
+    let assists = collect_assists(repo_config)?;
+    let contents = assists.into_iter().map(|it| it.to_string()).collect::<Vec<_>>().join("\n");
+    let contents = codegen::reformat(&contents)?;
+    let dst = project_root().join("docs/user/generated_assists.adoc");
+    codegen::update(&dst, &contents, mode)
+}
+
+pub(crate) fn generate_assists_tests(mode: Mode, repo_config: &RepoConfig) -> Result<()> {
+    let assists = collect_assists(repo_config)?;
+    let contents = assists
+        .into_iter()
+        .map(|it| format!("// {}\n// {}\n", it.id, it.location))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let contents = codegen::reformat(&contents)?;
+    let dst = project_root().join("crates/ide-assists/src/tests/generated.rs");
+    codegen::update(&dst, &contents, mode)
+}
+
+struct Assist {
+    id: String,
+    location: Location,
+    doc: String,
+}
+
+impl fmt::Display for Assist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== `{}`\n**Source:** {}\n\n{}", self.id, self.location, self.doc)
+    }
+}
+
+fn collect_assists(repo_config: &RepoConfig) -> Result<Vec<Assist>> {
+    let handlers_dir = project_root().join("crates/ide-assists/src/handlers");
+    let mut res = Vec::new();
+    for path in list_rust_files(&handlers_dir) {
+        collect_file(&mut res, path, repo_config)?;
+    }
+    res.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+    Ok(res)
+}
+
+fn collect_file(acc: &mut Vec<Assist>, path: PathBuf, repo_config: &RepoConfig) -> Result<()> {
+    let text = fs::read_to_string(&path)?;
+    for block in extract_comment_blocks_with_empty_lines("Assist", &text) {
+        let id = block.id;
+        assert!(
+            id.chars().all(|it| it.is_ascii_lowercase() || it == '_'),
+            "invalid assist id: {:?}",
+            id
+        );
+        let doc = block.contents.join("\n");
+        let location = Location::with_repo_config(path.clone(), block.line, repo_config.clone());
+        acc.push(Assist { id, location, doc });
+    }
+    Ok(())
+}
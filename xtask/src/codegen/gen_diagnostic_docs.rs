@@ -0,0 +1,52 @@
+//! Generates `generated_diagnostic.adoc` documentation from doc comments on
+//! diagnostics defined in `ide-diagnostics`.
+
+use std::{fmt, fs, path::PathBuf};
+
+use crate::{
+    codegen::{
+        self, extract_comment_blocks_with_empty_lines, list_rust_files, Location, Mode,
+        RepoConfig,
+    },
+    project_root, Result,
+};
+
+pub(crate) fn generate_diagnostic_docs(mode: Mode, repo_config: &RepoConfig) -> Result<()> {
+    let diagnostics = collect_diagnostics(repo_config)?;
+    let contents = diagnostics.into_iter().map(|it| it.to_string()).collect::<Vec<_>>().join("\n");
+    let contents = codegen::reformat(&contents)?;
+    let dst = project_root().join("docs/user/generated_diagnostic.adoc");
+    codegen::update(&dst, &contents, mode)
+}
+
+struct Diagnostic {
+    id: String,
+    location: Location,
+    doc: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "=== {}\n**Source:** {}\n\n{}", self.id, self.location, self.doc)
+    }
+}
+
+fn collect_diagnostics(repo_config: &RepoConfig) -> Result<Vec<Diagnostic>> {
+    let diagnostics_dir = project_root().join("crates/ide-diagnostics/src");
+    let mut res = Vec::new();
+    for path in list_rust_files(&diagnostics_dir) {
+        collect_file(&mut res, path, repo_config)?;
+    }
+    res.sort_by(|lhs, rhs| lhs.id.cmp(&rhs.id));
+    Ok(res)
+}
+
+fn collect_file(acc: &mut Vec<Diagnostic>, path: PathBuf, repo_config: &RepoConfig) -> Result<()> {
+    let text = fs::read_to_string(&path)?;
+    for block in extract_comment_blocks_with_empty_lines("Diagnostic", &text) {
+        let doc = block.contents.join("\n");
+        let location = Location::with_repo_config(path.clone(), block.line, repo_config.clone());
+        acc.push(Diagnostic { id: block.id, location, doc });
+    }
+    Ok(())
+}